@@ -0,0 +1,194 @@
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection, OptionalExtension};
+use std::collections::HashMap;
+
+/// Wraps the local SQLite database that stores watch history across runs.
+///
+/// Replaces the old `cutoff_date.txt` / `show_data.json` pair: instead of
+/// emitting a fresh, duplicated JSON dump every run, watched episodes are
+/// recorded once (via `INSERT OR IGNORE`) so counts accumulate over time.
+pub struct HistoryDb {
+    conn: Connection,
+}
+
+impl HistoryDb {
+    /// Opens (creating if necessary) the database at `path` and ensures the
+    /// schema is present.
+    pub fn open(path: &str) -> rusqlite::Result<Self> {
+        let conn = Connection::open(path)?;
+        let db = Self { conn };
+        db.init_schema()?;
+        Ok(db)
+    }
+
+    fn init_schema(&self) -> rusqlite::Result<()> {
+        self.conn.execute_batch(
+            "
+            CREATE TABLE IF NOT EXISTS series (
+                slug        TEXT PRIMARY KEY,
+                title       TEXT NOT NULL,
+                data        TEXT NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS episodes_watched (
+                series_slug   TEXT NOT NULL,
+                episode_id    TEXT NOT NULL,
+                episode_title TEXT NOT NULL,
+                locale        TEXT NOT NULL,
+                date_played   TEXT NOT NULL,
+                PRIMARY KEY (series_slug, episode_id)
+            );
+
+            CREATE TABLE IF NOT EXISTS sync_state (
+                id          INTEGER PRIMARY KEY CHECK (id = 0),
+                cutoff_date TEXT NOT NULL
+            );
+            ",
+        )?;
+        Ok(())
+    }
+
+    /// Reads the last cutoff timestamp recorded by a previous run, if any.
+    pub fn read_cutoff_date(&self) -> rusqlite::Result<Option<DateTime<Utc>>> {
+        let cutoff: Option<String> = self
+            .conn
+            .query_row(
+                "SELECT cutoff_date FROM sync_state WHERE id = 0",
+                [],
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        Ok(cutoff.and_then(|s| s.parse::<DateTime<Utc>>().ok()))
+    }
+
+    /// Persists the cutoff timestamp to use as the lower bound on the next run.
+    pub fn update_cutoff_date(&self, new_cutoff: DateTime<Utc>) -> rusqlite::Result<()> {
+        self.conn.execute(
+            "INSERT INTO sync_state (id, cutoff_date) VALUES (0, ?1)
+             ON CONFLICT(id) DO UPDATE SET cutoff_date = excluded.cutoff_date",
+            params![new_cutoff.to_rfc3339()],
+        )?;
+        Ok(())
+    }
+
+    /// Upserts a series' extracted metadata, keyed by slug.
+    pub fn upsert_series(&self, slug: &str, title: &str, data: &str) -> rusqlite::Result<()> {
+        self.conn.execute(
+            "INSERT INTO series (slug, title, data) VALUES (?1, ?2, ?3)
+             ON CONFLICT(slug) DO UPDATE SET title = excluded.title, data = excluded.data",
+            params![slug, title, data],
+        )?;
+        Ok(())
+    }
+
+    /// Records a watched episode. Duplicate `(series_slug, episode_id)` pairs
+    /// are silently ignored so re-running over the same history never
+    /// double-counts an episode.
+    pub fn record_episode_watched(
+        &self,
+        series_slug: &str,
+        episode_id: &str,
+        episode_title: &str,
+        locale: &str,
+        date_played: DateTime<Utc>,
+    ) -> rusqlite::Result<()> {
+        self.conn.execute(
+            "INSERT OR IGNORE INTO episodes_watched (series_slug, episode_id, episode_title, locale, date_played)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                series_slug,
+                episode_id,
+                episode_title,
+                locale,
+                date_played.to_rfc3339()
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Returns the total number of distinct episodes watched per series slug.
+    pub fn episode_counts_by_series(&self) -> rusqlite::Result<HashMap<String, u32>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT series_slug, COUNT(*) FROM episodes_watched GROUP BY series_slug",
+        )?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+
+        let mut counts = HashMap::new();
+        for row in rows {
+            let (slug, count) = row?;
+            counts.insert(slug, count);
+        }
+        Ok(counts)
+    }
+
+    /// Returns, for each series slug, a histogram of how many watched
+    /// episodes used each audio locale.
+    pub fn locale_histogram_by_series(
+        &self,
+    ) -> rusqlite::Result<HashMap<String, HashMap<String, u32>>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT series_slug, locale, COUNT(*) FROM episodes_watched
+             GROUP BY series_slug, locale",
+        )?;
+        let rows =
+            stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
+
+        let mut histogram: HashMap<String, HashMap<String, u32>> = HashMap::new();
+        for row in rows {
+            let (slug, locale, count): (String, String, u32) = row?;
+            histogram.entry(slug).or_default().insert(locale, count);
+        }
+        Ok(histogram)
+    }
+
+    /// Returns the stored metadata JSON for every series, keyed by slug.
+    pub fn all_series(&self) -> rusqlite::Result<HashMap<String, String>> {
+        let mut stmt = self.conn.prepare("SELECT slug, data FROM series")?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+
+        let mut series = HashMap::new();
+        for row in rows {
+            let (slug, data) = row?;
+            series.insert(slug, data);
+        }
+        Ok(series)
+    }
+
+    /// Returns the `limit` most recently watched episodes, joined with their
+    /// series metadata, ordered newest first. Used to build the RSS feed.
+    pub fn recent_watch_entries(&self, limit: u32) -> rusqlite::Result<Vec<FeedEntry>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT ew.series_slug, ew.episode_id, ew.episode_title, ew.date_played, s.title, s.data
+             FROM episodes_watched ew
+             JOIN series s ON s.slug = ew.series_slug
+             ORDER BY ew.date_played DESC
+             LIMIT ?1",
+        )?;
+
+        let rows = stmt.query_map(params![limit], |row| {
+            let date_played: String = row.get(3)?;
+            Ok(FeedEntry {
+                series_slug: row.get(0)?,
+                episode_id: row.get(1)?,
+                episode_title: row.get(2)?,
+                date_played,
+                series_title: row.get(4)?,
+                series_data: row.get(5)?,
+            })
+        })?;
+
+        rows.collect()
+    }
+}
+
+/// A single watched episode joined with its series metadata, shaped for feed
+/// generation.
+pub struct FeedEntry {
+    pub series_slug: String,
+    pub episode_id: String,
+    pub episode_title: String,
+    pub date_played: String,
+    pub series_title: String,
+    pub series_data: String,
+}
@@ -0,0 +1,102 @@
+use crate::db::FeedEntry;
+use chrono::DateTime;
+use quick_xml::events::{BytesEnd, BytesStart, BytesText, Event};
+use quick_xml::writer::Writer;
+use std::io::Cursor;
+
+/// Builds a standards-compliant RSS 2.0 feed of recently watched episodes.
+///
+/// One `<item>` per watch-history entry, following the same shape as
+/// rss-anime-notifier-rs: `<title>` is `"<series title> - <episode title>"`, `<pubDate>` is
+/// `date_played` in RFC-2822, a `<guid>` of `series_slug/episode_id` lets
+/// feed readers dedupe entries across refreshes, and the series' `posterTall`
+/// image is attached as both a `<media:thumbnail>` and an `<enclosure>`.
+pub fn build_feed(entries: &[FeedEntry]) -> quick_xml::Result<String> {
+    let mut writer = Writer::new_with_indent(Cursor::new(Vec::new()), b' ', 2);
+
+    let mut rss_start = BytesStart::new("rss");
+    rss_start.push_attribute(("version", "2.0"));
+    rss_start.push_attribute(("xmlns:media", "http://search.yahoo.com/mrss/"));
+    writer.write_event(Event::Start(rss_start))?;
+
+    writer.write_event(Event::Start(BytesStart::new("channel")))?;
+    write_text_element(&mut writer, "title", "Crunchyroll Watch History")?;
+    write_text_element(&mut writer, "link", "https://www.crunchyroll.com/history")?;
+    write_text_element(
+        &mut writer,
+        "description",
+        "Recently watched episodes, tracked locally.",
+    )?;
+
+    for entry in entries {
+        write_item(&mut writer, entry)?;
+    }
+
+    writer.write_event(Event::End(BytesEnd::new("channel")))?;
+    writer.write_event(Event::End(BytesEnd::new("rss")))?;
+
+    let bytes = writer.into_inner().into_inner();
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+fn write_item<W: std::io::Write>(
+    writer: &mut Writer<W>,
+    entry: &FeedEntry,
+) -> quick_xml::Result<()> {
+    let series_data: serde_json::Value =
+        serde_json::from_str(&entry.series_data).unwrap_or(serde_json::Value::Null);
+
+    let description = series_data
+        .get("description")
+        .and_then(|v| v.as_str())
+        .unwrap_or_default();
+    let poster_tall = series_data
+        .get("posterTall")
+        .and_then(|v| v.as_str())
+        .unwrap_or_default();
+
+    let pub_date = DateTime::parse_from_rfc3339(&entry.date_played)
+        .map(|date| date.to_rfc2822())
+        .unwrap_or_default();
+
+    let title = format!("{} - {}", entry.series_title, entry.episode_title);
+    let guid = format!("{}/{}", entry.series_slug, entry.episode_id);
+
+    writer.write_event(Event::Start(BytesStart::new("item")))?;
+    write_text_element(writer, "title", &title)?;
+    write_text_element(writer, "description", description)?;
+    write_text_element(writer, "pubDate", &pub_date)?;
+
+    let mut guid_start = BytesStart::new("guid");
+    guid_start.push_attribute(("isPermaLink", "false"));
+    writer.write_event(Event::Start(guid_start))?;
+    writer.write_event(Event::Text(BytesText::new(&guid)))?;
+    writer.write_event(Event::End(BytesEnd::new("guid")))?;
+
+    if !poster_tall.is_empty() {
+        let mut thumbnail = BytesStart::new("media:thumbnail");
+        thumbnail.push_attribute(("url", poster_tall));
+        writer.write_event(Event::Empty(thumbnail))?;
+
+        let mut enclosure = BytesStart::new("enclosure");
+        enclosure.push_attribute(("url", poster_tall));
+        // The image's byte size is not known here; RSS requires the attribute.
+        enclosure.push_attribute(("length", "0"));
+        enclosure.push_attribute(("type", "image/jpeg"));
+        writer.write_event(Event::Empty(enclosure))?;
+    }
+
+    writer.write_event(Event::End(BytesEnd::new("item")))?;
+    Ok(())
+}
+
+fn write_text_element<W: std::io::Write>(
+    writer: &mut Writer<W>,
+    tag: &str,
+    text: &str,
+) -> quick_xml::Result<()> {
+    writer.write_event(Event::Start(BytesStart::new(tag)))?;
+    writer.write_event(Event::Text(BytesText::new(text)))?;
+    writer.write_event(Event::End(BytesEnd::new(tag)))?;
+    Ok(())
+}
@@ -0,0 +1,27 @@
+/// Derives the dub/audio locale the user actually watched from an episode's
+/// (or series') slug title.
+///
+/// Follows the `parse_locale_from_slug_title` technique from crunchyroll-rs:
+/// Crunchyroll encodes the dub language as a `-dub` suffixed slug, e.g.
+/// `attack-on-titan-german-dub`. Trim the trailing `-dub`, then match the
+/// remaining language suffix to a locale. Slugs with no such suffix are
+/// assumed to be the show's original, subtitled audio.
+pub fn parse_locale_from_slug_title(slug_title: &str, original_locale: &str) -> String {
+    let Some(without_dub) = slug_title.strip_suffix("-dub") else {
+        return original_locale.to_string();
+    };
+
+    let locale = LOCALE_SUFFIXES
+        .iter()
+        .find_map(|(suffix, locale)| without_dub.strip_suffix(suffix).map(|_| *locale));
+
+    locale.unwrap_or(original_locale).to_string()
+}
+
+const LOCALE_SUFFIXES: &[(&str, &str)] = &[
+    ("-german", "de_DE"),
+    ("-french", "fr_FR"),
+    ("-hindi", "hi_IN"),
+    ("-castilian", "es_ES"),
+    ("-english", "en_US"),
+];
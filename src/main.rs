@@ -1,41 +1,33 @@
+mod db;
+mod feed;
+mod locale;
+mod metrics;
+mod recommendations;
+
 use chrono::{DateTime, Utc};
 use crunchyroll_rs::{common::StreamExt, Crunchyroll, MediaCollection};
+use db::HistoryDb;
 use dotenvy::dotenv;
+use futures::stream::{self, StreamExt as _};
+use metrics::RunMetrics;
 use serde_json::json;
+use std::collections::HashSet;
 use std::env;
-use std::{
-    collections::HashMap,
-    fs::{self, File, OpenOptions},
-    io::{self, Write},
-    path::Path,
-};
-
-/// Reads the last cutoff date from `cutoff_date.txt`, if it exists.
-fn read_cutoff_date() -> Result<Option<DateTime<Utc>>, io::Error> {
-    let filename = "cutoff_date.txt";
-
-    match fs::read_to_string(filename) {
-        Ok(contents) => {
-            let date_str = contents.trim();
-            if date_str.is_empty() {
-                return Ok(None);
-            }
-            match date_str.parse::<DateTime<Utc>>() {
-                Ok(date) => Ok(Some(date)),
-                Err(_) => Err(io::Error::new(
-                    io::ErrorKind::InvalidData,
-                    "Invalid date format in cutoff_date.txt",
-                )),
-            }
-        }
-        Err(err) => {
-            if err.kind() == io::ErrorKind::NotFound {
-                return Ok(None);
-            }
-            Err(err)
-        }
-    }
-}
+use std::fs::File;
+use std::io::Write;
+
+/// Maximum number of recent entries to include in the RSS feed.
+const FEED_ITEM_LIMIT: u32 = 100;
+
+/// Default number of `media_collection_from_id` lookups to drive concurrently
+/// when resolving pre-fetched watch-history entries.
+const DEFAULT_CONCURRENCY: usize = 8;
+
+/// Crunchyroll's `watch_history()` is not guaranteed to be perfectly sorted,
+/// and stale/interleaved entries can appear ahead of the cutoff. Only stop
+/// pre-fetching after this many *consecutive* out-of-range entries, so a
+/// single stale entry doesn't truncate the run.
+const CUTOFF_TOLERANCE: u32 = 3;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -51,8 +43,15 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .login_with_credentials(username, password)
         .await?;
 
-    // Read the last cutoff date from file
-    let cutoff_date = match read_cutoff_date() {
+    let db_path = env::var("CR_DB_PATH").unwrap_or_else(|_| "history.sqlite3".to_string());
+    let db = HistoryDb::open(&db_path)?;
+
+    // Tracks entries processed and media_collection lookup outcomes for the
+    // whole run; reports a summary when it drops at the end of `main`.
+    let run_metrics = RunMetrics::new();
+
+    // Read the last cutoff date from the database
+    let cutoff_date = match db.read_cutoff_date() {
         Ok(Some(date)) => {
             println!("Previous cutoff date: {}", date);
             Some(date)
@@ -71,129 +70,232 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     };
 
     let mut history_stream = crunchyroll.watch_history();
-    let mut title_episode_counts: HashMap<String, u32> = HashMap::new();
-    let mut extracted_series_data: HashMap<String, serde_json::Value> = HashMap::new();
 
     // Get the current UTC time to update the cutoff date at the end
     let script_run_time = Utc::now();
     println!("Script started at: {}", script_run_time);
 
-    let mut total_show_count = 0;
-
+    // Pre-fetch stage: drain the history stream (which is itself paginated
+    // network I/O) without resolving any media collections yet, so the
+    // cutoff/limit short-circuit happens before the expensive part of the
+    // run. `entry.date_played` is available on the history entry directly,
+    // so this needs no `media_collection_from_id` calls.
+    let mut pending_entries = Vec::new();
+    let mut consecutive_out_of_range = 0;
+    let mut scanned_count = 0;
     while let Some(entry_result) = history_stream.next().await {
         if let Some(limit_value) = limit {
-            if total_show_count >= limit_value {
+            if scanned_count >= limit_value {
                 break;
             }
         }
 
         match entry_result {
             Ok(entry) => {
-                let entry_date: DateTime<Utc> = entry.date_played;
-
-                let media_collection = crunchyroll
-                    .media_collection_from_id(entry.parent_id)
-                    .await?;
-
-                let title = match &media_collection {
-                    MediaCollection::Movie(movie) => movie.title.clone(),
-                    MediaCollection::Series(series) => series.title.clone(),
-                    MediaCollection::Episode(episode) => episode.title.clone(),
-                    _ => continue,
-                };
-
-                // Stop processing if the entry is before the cutoff date
+                scanned_count += 1;
                 if let Some(cutoff) = cutoff_date {
-                    if entry_date < cutoff {
+                    if entry.date_played < cutoff {
+                        consecutive_out_of_range += 1;
                         println!(
-                            "Stopping: Show watched before cutoff ({}). Title: {}",
-                            cutoff, title
+                            "Entry watched before cutoff ({}); {}/{} consecutive",
+                            cutoff, consecutive_out_of_range, CUTOFF_TOLERANCE
                         );
-                        break;
+                        if consecutive_out_of_range >= CUTOFF_TOLERANCE {
+                            println!("Stopping pre-fetch: cutoff tolerance reached");
+                            break;
+                        }
+                        continue;
                     }
                 }
+                consecutive_out_of_range = 0;
+                pending_entries.push(entry);
+            }
+            Err(err) => eprintln!("Error fetching watch history entry: {:?}", err),
+        }
+    }
 
-                // Update episode count
-                let episode_count = title_episode_counts.entry(title.clone()).or_insert(0);
-                *episode_count += 1;
-
-                // If we haven't extracted this series yet, do it now
-                if let MediaCollection::Series(series) = &media_collection {
-                    if !extracted_series_data.contains_key(&title) {
-                        let series_data = json!({
-                            "title": series.title,
-                            "slug": series.slug_title,
-                            "description": series.description,
-                            "extendedDescription": series.extended_description,
-                            "episodes": series.episode_count,
-                            "seasons": series.season_count,
-                            "publisher": series.content_provider.clone().unwrap_or("Unknown".to_string()),
-                            "keywords": series.keywords,
-                            "posterTall": series.images.poster_tall
-                                .get(2)
-                                .map(|img| img.source.clone())
-                                .unwrap_or("No image available".to_string())
-                        });
-
-                        extracted_series_data.insert(title.clone(), series_data);
-                    }
-                }
+    let concurrency = env::var("CR_CONCURRENCY")
+        .ok()
+        .and_then(|v| v.parse::<usize>().ok())
+        .unwrap_or(DEFAULT_CONCURRENCY);
+
+    let mut total_show_count = 0;
+    let mut watched_series = Vec::new();
+    let mut seen_series_slugs = HashSet::new();
 
-                println!("{}: {} episodes watched", title, episode_count);
-                total_show_count += 1;
+    let resolved = stream::iter(pending_entries)
+        .map(|entry| {
+            let crunchyroll = &crunchyroll;
+            async move {
+                let entry_date: DateTime<Utc> = entry.date_played;
+                // Borrow `parent_id` rather than moving it out, since `entry`
+                // is read again below (`parent_id`, `slug_title`, `id`).
+                let result = crunchyroll
+                    .media_collection_from_id(&entry.parent_id)
+                    .await;
+                (entry, entry_date, result)
             }
-            Err(err) => eprintln!("Error fetching watch history entry: {:?}", err),
+        })
+        .buffer_unordered(concurrency)
+        .collect::<Vec<_>>()
+        .await;
+
+    for (entry, entry_date, media_collection_result) in resolved {
+        run_metrics.record_entry_processed();
+        run_metrics.record_media_collection_result(&media_collection_result);
+
+        let media_collection = match media_collection_result {
+            Ok(media_collection) => media_collection,
+            Err(err) => {
+                eprintln!("Error resolving media collection: {:?}", err);
+                continue;
+            }
+        };
+
+        let title = match &media_collection {
+            MediaCollection::Movie(movie) => movie.title.clone(),
+            MediaCollection::Series(series) => series.title.clone(),
+            MediaCollection::Episode(episode) => episode.title.clone(),
+            _ => continue,
+        };
+
+        // `watch_history()` is known to interleave empty, placeholder Series
+        // entries between real ones. Skip them instead of recording a blank
+        // key in the output.
+        if title.trim().is_empty() {
+            println!(
+                "Skipping placeholder entry with empty title (parent_id: {})",
+                entry.parent_id
+            );
+            continue;
         }
+
+        if let MediaCollection::Series(series) = &media_collection {
+            // Series metadata only needs writing once per slug per run; the
+            // DB calls below are idempotent (upsert / `INSERT OR IGNORE`)
+            // regardless, but skipping repeat work keeps `watched_series`
+            // (used for recommendations) from holding one entry per episode.
+            if seen_series_slugs.insert(series.slug_title.clone()) {
+                let series_data = json!({
+                    "title": series.title,
+                    "slug": series.slug_title,
+                    "description": series.description,
+                    "extendedDescription": series.extended_description,
+                    "episodes": series.episode_count,
+                    "seasons": series.season_count,
+                    "publisher": series.content_provider.clone().unwrap_or("Unknown".to_string()),
+                    "keywords": series.keywords,
+                    "posterTall": series.images.poster_tall
+                        .get(2)
+                        .map(|img| img.source.clone())
+                        .unwrap_or("No image available".to_string())
+                });
+
+                db.upsert_series(&series.slug_title, &series.title, &series_data.to_string())?;
+                watched_series.push(series.clone());
+            }
+
+            // The watch-history entry carries the episode's own slug title,
+            // which is what actually encodes the dub the user watched; the
+            // series' first listed audio locale is its original/primary
+            // audio, used as the fallback when the episode slug carries no
+            // dub suffix.
+            let original_locale = series
+                .audio_locales
+                .first()
+                .map(|locale| locale.to_string())
+                .unwrap_or_default();
+            let watched_locale =
+                locale::parse_locale_from_slug_title(&entry.slug_title, &original_locale);
+
+            db.record_episode_watched(
+                &series.slug_title,
+                &entry.id,
+                &title,
+                &watched_locale,
+                entry_date,
+            )?;
+        }
+
+        println!("{}: watched on {}", title, entry_date);
+        total_show_count += 1;
     }
 
-    let filename = get_unique_filename("show_data.json");
-    let mut file = File::create(&filename)?;
+    // Build the per-series output from accumulated history, not just this run
+    let episode_counts = db.episode_counts_by_series()?;
+    let locale_histograms = db.locale_histogram_by_series()?;
+    let all_series = db.all_series()?;
 
-    // Create JSON structure combining series data and episodes watched
-    let output_data: Vec<_> = extracted_series_data
+    let output_data: Vec<_> = all_series
         .into_iter()
-        .map(|(title, series_data)| {
-            let episodes_watched = title_episode_counts.get(&title).cloned().unwrap_or(0);
+        .map(|(slug, data)| {
+            let series_data: serde_json::Value =
+                serde_json::from_str(&data).unwrap_or(serde_json::Value::Null);
+            let episodes_watched = episode_counts.get(&slug).cloned().unwrap_or(0);
+            let locales_watched = locale_histograms.get(&slug).cloned().unwrap_or_default();
             json!({
                 "series": series_data,
-                "episodesWatched": episodes_watched
+                "episodesWatched": episodes_watched,
+                "localesWatched": locales_watched
             })
         })
         .collect();
 
-    writeln!(file, "{}", serde_json::to_string_pretty(&output_data)?)?;
+    println!(
+        "Processed {} entries this run. Accumulated watch history for {} series (database: {})",
+        total_show_count,
+        output_data.len(),
+        db_path
+    );
 
-    println!("Extracted data saved to: {}", filename);
-    println!("Finished processing! Check {} for results.", filename);
+    match output_format().as_str() {
+        "rss" => {
+            let recent_entries = db.recent_watch_entries(FEED_ITEM_LIMIT)?;
+            let xml = feed::build_feed(&recent_entries)?;
+            let mut file = File::create("history.rss")?;
+            write!(file, "{}", xml)?;
+            println!("RSS feed written to history.rss");
+        }
+        _ => {
+            let mut file = File::create("show_data.json")?;
+            writeln!(file, "{}", serde_json::to_string_pretty(&output_data)?)?;
+            println!("Extracted data saved to: show_data.json");
+        }
+    }
 
-    // Update the cutoff date for next run
-    update_cutoff_date(script_run_time)?;
+    if !watched_series.is_empty() {
+        let watched_slugs: HashSet<String> = db.all_series()?.into_keys().collect();
+        let recommendations = recommendations::build_recommendations(
+            &watched_series,
+            &episode_counts,
+            &watched_slugs,
+        )
+        .await;
 
-    Ok(())
-}
+        let mut file = File::create("recommendations.json")?;
+        writeln!(file, "{}", serde_json::to_string_pretty(&recommendations)?)?;
+        println!(
+            "Generated {} recommendations from {} watched series -> recommendations.json",
+            recommendations.len(),
+            watched_series.len()
+        );
+    }
 
-/// Updates the `cutoff_date.txt` with the new script run time.
-fn update_cutoff_date(new_cutoff: DateTime<Utc>) -> io::Result<()> {
-    let mut file = OpenOptions::new()
-        .write(true)
-        .create(true)
-        .truncate(true)
-        .open("cutoff_date.txt")?;
+    // Update the cutoff date for next run
+    db.update_cutoff_date(script_run_time)?;
+    println!("✅ Updated cutoff date to: {}", script_run_time);
 
-    writeln!(file, "{}", new_cutoff)?;
-    println!("✅ Updated cutoff date to: {}", new_cutoff);
     Ok(())
 }
 
-/// Generates a unique filename by appending a number if the file already exists.
-fn get_unique_filename(base_name: &str) -> String {
-    let mut counter = 1;
-    let mut new_name = format!("{}.json", base_name.trim_end_matches(".json"));
-
-    while Path::new(&new_name).exists() {
-        new_name = format!("{}-{}.json", base_name.trim_end_matches(".json"), counter);
-        counter += 1;
-    }
+/// Determines the output format: a `--format=rss` CLI flag takes priority
+/// over the `OUTPUT_FORMAT` env var, defaulting to `"json"`.
+fn output_format() -> String {
+    let cli_format = env::args()
+        .find_map(|arg| arg.strip_prefix("--format=").map(str::to_string));
 
-    new_name
+    cli_format
+        .or_else(|| env::var("OUTPUT_FORMAT").ok())
+        .unwrap_or_else(|| "json".to_string())
+        .to_lowercase()
 }
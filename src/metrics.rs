@@ -0,0 +1,89 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+
+/// Tracks run-level counters and reports them on drop.
+///
+/// Modeled on pict-rs' `generate.rs` metrics-guard pattern: construct the
+/// guard at the start of a unit of work, record outcomes into it as the work
+/// happens, and let `Drop` print the summary (entries processed, success vs.
+/// error counts, elapsed time, throughput) regardless of which path out of
+/// `main` is taken.
+pub struct RunMetrics {
+    start: Instant,
+    entries_processed: AtomicU64,
+    media_collection_successes: AtomicU64,
+    media_collection_errors: AtomicU64,
+}
+
+impl Default for RunMetrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RunMetrics {
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            entries_processed: AtomicU64::new(0),
+            media_collection_successes: AtomicU64::new(0),
+            media_collection_errors: AtomicU64::new(0),
+        }
+    }
+
+    pub fn record_entry_processed(&self) {
+        self.entries_processed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_media_collection_result<T, E>(&self, result: &Result<T, E>) {
+        match result {
+            Ok(_) => self.media_collection_successes.fetch_add(1, Ordering::Relaxed),
+            Err(_) => self.media_collection_errors.fetch_add(1, Ordering::Relaxed),
+        };
+
+        #[cfg(feature = "metrics")]
+        {
+            let label = if result.is_ok() { "success" } else { "error" };
+            metrics::counter!("cr_history_media_collection_lookups_total", "result" => label)
+                .increment(1);
+        }
+    }
+}
+
+impl Drop for RunMetrics {
+    fn drop(&mut self) {
+        let elapsed = self.start.elapsed();
+        let entries = self.entries_processed.load(Ordering::Relaxed);
+        let successes = self.media_collection_successes.load(Ordering::Relaxed);
+        let errors = self.media_collection_errors.load(Ordering::Relaxed);
+        let throughput = entries as f64 / elapsed.as_secs_f64().max(f64::MIN_POSITIVE);
+
+        #[cfg(feature = "metrics")]
+        {
+            tracing::info!(
+                entries,
+                successes,
+                errors,
+                elapsed_secs = elapsed.as_secs_f64(),
+                throughput,
+                "run finished"
+            );
+
+            metrics::gauge!("cr_history_run_elapsed_seconds").set(elapsed.as_secs_f64());
+            metrics::gauge!("cr_history_run_throughput_entries_per_sec").set(throughput);
+        }
+
+        println!(
+            "--- Run summary ---\n\
+             Entries processed: {entries}\n\
+             media_collection_from_id: {successes} ok, {errors} errored\n\
+             Elapsed: {elapsed:.2?}\n\
+             Throughput: {throughput:.2} entries/sec",
+            entries = entries,
+            successes = successes,
+            errors = errors,
+            elapsed = elapsed,
+            throughput = throughput
+        );
+    }
+}
@@ -0,0 +1,106 @@
+use crunchyroll_rs::{common::StreamExt, MediaCollection, Series};
+use serde_json::json;
+use std::collections::{HashMap, HashSet};
+
+/// Maximum number of similar titles to pull per watched series.
+const SIMILAR_PER_SERIES: usize = 20;
+
+/// Builds a ranked "you might like" list from the series the user has
+/// watched, using crunchyroll-rs' `Series::similar` API.
+///
+/// Each recommended title is weighted by the sum of episodes watched across
+/// every source series that recommended it, titles already present in
+/// `watched_slugs` are dropped, and the result is sorted by descending
+/// weight.
+pub async fn build_recommendations(
+    watched_series: &[Series],
+    episode_counts: &HashMap<String, u32>,
+    watched_slugs: &HashSet<String>,
+) -> Vec<serde_json::Value> {
+    let mut weighted: HashMap<String, (serde_json::Value, u32)> = HashMap::new();
+
+    // Nothing here requires `watched_series` to be unique by slug; dedupe so
+    // each source series is only queried via `similar()` once and only
+    // contributes its weight once.
+    let mut unique_watched_series = HashMap::new();
+    for series in watched_series {
+        unique_watched_series
+            .entry(series.slug_title.clone())
+            .or_insert(series);
+    }
+
+    for series in unique_watched_series.into_values() {
+        let weight = episode_counts.get(&series.slug_title).copied().unwrap_or(1);
+
+        let mut similar_stream = series.similar();
+        let mut pulled = 0;
+        while pulled < SIMILAR_PER_SERIES {
+            let Some(similar_result) = similar_stream.next().await else {
+                break;
+            };
+            pulled += 1;
+
+            let similar = match similar_result {
+                Ok(similar) => similar,
+                Err(err) => {
+                    eprintln!(
+                        "Error fetching similar titles for {}: {:?}",
+                        series.title, err
+                    );
+                    continue;
+                }
+            };
+
+            let MediaCollection::Series(similar_series) = similar else {
+                continue;
+            };
+
+            if watched_slugs.contains(&similar_series.slug_title) {
+                continue;
+            }
+
+            let poster = similar_series
+                .images
+                .poster_tall
+                .get(2)
+                .map(|img| img.source.clone())
+                .unwrap_or("No image available".to_string());
+
+            let entry = weighted
+                .entry(similar_series.slug_title.clone())
+                .or_insert_with(|| {
+                    (
+                        json!({
+                            "title": similar_series.title,
+                            "slug": similar_series.slug_title,
+                            "poster": poster,
+                            "sourceSeries": Vec::<String>::new()
+                        }),
+                        0,
+                    )
+                });
+
+            entry.1 += weight;
+            if let Some(sources) = entry.0.get_mut("sourceSeries").and_then(|v| v.as_array_mut())
+            {
+                sources.push(json!(series.title));
+            }
+        }
+    }
+
+    let mut recommendations: Vec<_> = weighted
+        .into_values()
+        .map(|(mut data, weight)| {
+            data["weight"] = json!(weight);
+            data
+        })
+        .collect();
+
+    recommendations.sort_by(|a, b| {
+        let weight_a = a["weight"].as_u64().unwrap_or(0);
+        let weight_b = b["weight"].as_u64().unwrap_or(0);
+        weight_b.cmp(&weight_a)
+    });
+
+    recommendations
+}